@@ -0,0 +1,90 @@
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use rosserial_rs::RosSerialMsg;
+use rosserial_rs::bench::{RosSerialMsgCodec, calc_checksum};
+use std::hint::black_box;
+use tokio_util::bytes::BytesMut;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Representative message sizes: from small (e.g. time sync) up to large payloads (e.g. images / point clouds).
+const SIZES: [usize; 5] = [8, 64, 512, 4096, 16384];
+
+const TOPIC: u16 = 100;
+
+fn msg(size: usize) -> RosSerialMsg {
+    RosSerialMsg {
+        topic: TOPIC,
+        msg: (0..size).map(|i| i as u8).collect(),
+    }
+}
+
+/// The original implementation of the checksum (with a modulo per byte), to compare against.
+fn calc_checksum_modulo(bytes: impl Iterator<Item = u8>) -> u8 {
+    let mut checksum: u16 = 0;
+    for byte in bytes {
+        checksum += byte as u16;
+        checksum %= 256;
+    }
+    checksum as u8
+}
+
+fn bench_checksum(c: &mut Criterion) {
+    let mut group = c.benchmark_group("checksum");
+    for size in SIZES {
+        let bytes = msg(size).msg;
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("modulo", size), &bytes, |b, bytes| {
+            b.iter(|| calc_checksum_modulo(black_box(bytes.as_slice()).iter().copied()))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("wrapping_add", size),
+            &bytes,
+            |b, bytes| b.iter(|| calc_checksum(black_box(bytes.as_slice()).iter().copied())),
+        );
+    }
+    group.finish();
+}
+
+fn bench_encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+    for size in SIZES {
+        let msg = msg(size);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &msg, |b, msg| {
+            let mut dst = BytesMut::with_capacity(size + 8);
+            b.iter_batched(
+                || msg.clone(),
+                |msg| {
+                    dst.clear();
+                    RosSerialMsgCodec.encode(black_box(msg), &mut dst).unwrap();
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for size in SIZES {
+        let mut frame = BytesMut::new();
+        RosSerialMsgCodec.encode(msg(size), &mut frame).unwrap();
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &frame, |b, frame| {
+            b.iter_batched(
+                || frame.clone(),
+                |mut src| {
+                    RosSerialMsgCodec
+                        .decode(black_box(&mut src))
+                        .unwrap()
+                        .unwrap()
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_checksum, bench_encode, bench_decode);
+criterion_main!(benches);
@@ -1,5 +1,8 @@
+//! The codec used to (de-)serialize [`RosSerialMsg`]s on the serial connection.
+
 use crate::RosSerialMsg;
 use Error::*;
+use log::{debug, trace, warn};
 use std::fmt::{Display, Formatter};
 use tokio_util::bytes::{Buf, BufMut, BytesMut};
 use tokio_util::codec::{Decoder, Encoder};
@@ -8,11 +11,15 @@ use tokio_util::codec::{Decoder, Encoder};
 #[derive(Debug)]
 pub enum Error {
     /// The message did not use a protocol version supported by the current implementation.
+    ///
+    /// The decoder does not return this (nor the checksum errors below), the invalid frames are logged and skipped.
     WrongProtocolVersion(u8),
     /// The checksum validation for the message length failed.
     InvalidLengthChecksum(u8),
     /// The checksum validation for the message body failed.
     InvalidMessageChecksum(u8),
+    /// The message body is too long to be sent (the protocol supports at most [`u16::MAX`] bytes).
+    MessageTooLong(usize),
     /// An underlying IO error occurred.
     #[allow(clippy::enum_variant_names)]
     IoError(std::io::Error),
@@ -42,6 +49,12 @@ impl Display for Error {
                 "invalid message checksum: computed {} but expected 255",
                 checksum
             ),
+            MessageTooLong(len) => write!(
+                f,
+                "message too long: {} bytes (max: {} bytes)",
+                len,
+                u16::MAX
+            ),
             IoError(_) => write!(f, "IO error"),
         }
     }
@@ -62,93 +75,108 @@ pub type Result<T> = std::result::Result<T, Error>;
 const HEADER: u8 = b'\xff';
 const PROTOCOL_VERSION_2: u8 = b'\xfe';
 
+/// Codec for the rosserial protocol (version 2).
+///
+/// Every message starts with the header `\xff` followed by the protocol version `\xfe`. Normal messages then contain
+/// the length of the message body (plus a checksum over it), the topic id, the serialized message and finally a
+/// checksum over the topic id & message. The request for the topics sent to the device is such a message as well: an
+/// empty message on [`TopicInfo::ID_PUBLISHER`](rosrust_msg::rosserial_msgs::TopicInfo::ID_PUBLISHER).
+#[derive(Debug)]
 pub struct RosSerialMsgCodec;
 
-fn calc_checksum(bytes: impl Iterator<Item = u8>) -> u8 {
-    let mut checksum: u16 = 0;
-    for byte in bytes {
-        checksum += byte as u16;
-        checksum %= 256;
-    }
-    checksum as u8
+/// Sum up the bytes (modulo 256).
+pub fn calc_checksum(bytes: impl Iterator<Item = u8>) -> u8 {
+    bytes.fold(0, u8::wrapping_add)
 }
 
-impl Decoder for RosSerialMsgCodec {
-    type Item = RosSerialMsg;
-    type Error = Error;
+/// Length of the frame before the message body: header, protocol version, length, length checksum & topic id.
+const FRAME_PREFIX_LEN: usize = 7;
 
-    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>> {
-        let start_marker = src.as_ref().iter().position(|b| *b == b'\xff');
-        if let Some(n) = start_marker {
-            // Throw away trash before the marker - we are not interested in it. Also skip the marker itself
-            src.advance(n + 1);
-        } else {
-            return Ok(None);
-        }
-
-        match src.try_get_u8() {
-            Ok(PROTOCOL_VERSION_2) => {}
-            Ok(version) => return Err(WrongProtocolVersion(version)),
-            Err(_) => return Ok(None),
-        }
+/// Parse the frame at the start of `buf` (which must start with [`HEADER`]).
+///
+/// Returns the message and the number of bytes it took up or [`None`] if the frame is not yet complete.
+fn parse_frame(buf: &[u8]) -> Result<Option<(RosSerialMsg, usize)>> {
+    match buf.get(1) {
+        Some(&PROTOCOL_VERSION_2) => {}
+        Some(&version) => return Err(WrongProtocolVersion(version)),
+        None => return Ok(None),
+    }
 
-        let len = match src.try_get_i16_le() {
-            Ok(len) => len,
-            Err(_) => return Ok(None),
-        };
-        let checksum_filler = match src.try_get_u8() {
-            Ok(checksum_filler) => checksum_filler,
-            Err(_) => return Ok(None),
-        };
-        // validate len checksum
-        let checksum = calc_checksum(
-            len.to_le_bytes()
-                .iter()
-                .chain(checksum_filler.to_le_bytes().iter())
-                .copied(),
-        );
-        if checksum != 255 {
-            return Err(InvalidLengthChecksum(checksum));
-        }
+    if buf.len() < 5 {
+        return Ok(None);
+    }
+    // validate len checksum
+    let checksum = calc_checksum(buf[2..5].iter().copied());
+    if checksum != 255 {
+        return Err(InvalidLengthChecksum(checksum));
+    }
+    let len = u16::from_le_bytes([buf[2], buf[3]]) as usize;
 
-        let topic_id = match src.try_get_u16_le() {
-            Ok(topic_id) => topic_id,
-            Err(_) => return Ok(None),
-        };
+    let data_end = FRAME_PREFIX_LEN + len;
+    if buf.len() < data_end {
+        return Ok(None);
+    }
+    let topic_id = u16::from_le_bytes([buf[5], buf[6]]);
+    let data_sum = calc_checksum(buf[5..data_end].iter().copied());
 
-        if src.len() < len as usize {
-            return Ok(None);
+    // data checksum, skipping any zero padding unless the zero is the valid checksum itself
+    for (i, data_checksum) in buf[data_end..].iter().enumerate() {
+        let checksum = data_sum.wrapping_add(*data_checksum);
+        if checksum == 255 {
+            let msg = RosSerialMsg {
+                topic: topic_id,
+                msg: buf[FRAME_PREFIX_LEN..data_end].to_vec(),
+            };
+            return Ok(Some((msg, data_end + i + 1)));
+        }
+        if *data_checksum != 0 {
+            return Err(InvalidMessageChecksum(checksum));
         }
-        let data = src.split_to(len as usize).to_vec();
+    }
+    Ok(None)
+}
 
-        // data checksum
-        let data_checksum = loop {
-            let data_checksum = match src.try_get_u8() {
-                Ok(data_checksum) => data_checksum,
-                Err(_) => return Ok(None),
-            };
-            if data_checksum != 0 {
-                break data_checksum;
-            }
-        };
+/// Invalid frames (e.g. the remainder of a frame sent before the port was opened) are logged and skipped, the decoder
+/// then continues with the next header. Thus decoding only fails on IO errors.
+impl Decoder for RosSerialMsgCodec {
+    type Item = RosSerialMsg;
+    type Error = Error;
 
-        let checksum = calc_checksum(
-            topic_id
-                .to_le_bytes()
-                .iter()
-                .chain(data.iter())
-                .chain(data_checksum.to_le_bytes().iter())
-                .copied(),
-        );
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>> {
+        loop {
+            // Throw away trash before the marker - we are not interested in it
+            match src.iter().position(|b| *b == HEADER) {
+                Some(n) => src.advance(n),
+                None => {
+                    src.clear();
+                    return Ok(None);
+                }
+            }
 
-        if checksum != 255 {
-            return Err(InvalidMessageChecksum(checksum));
+            match parse_frame(src) {
+                Ok(Some((msg, frame_len))) => {
+                    src.advance(frame_len);
+                    return Ok(Some(msg));
+                }
+                Ok(None) => return Ok(None),
+                Err(e @ WrongProtocolVersion(_)) => trace!("skipping garbage: {}", e),
+                Err(e) => warn!("skipping invalid frame: {}", e),
+            }
+            // skip the header of the invalid frame and look for the next one
+            src.advance(1);
         }
+    }
 
-        Ok(Some(RosSerialMsg {
-            topic: Some(topic_id),
-            msg: data,
-        }))
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>> {
+        let msg = self.decode(src)?;
+        if msg.is_none() && !src.is_empty() {
+            debug!(
+                "dropping incomplete frame at the end of the stream: {:?}",
+                src
+            );
+            src.clear();
+        }
+        Ok(msg)
     }
 }
 
@@ -156,22 +184,18 @@ impl Encoder<RosSerialMsg> for RosSerialMsgCodec {
     type Error = Error;
 
     fn encode(&mut self, item: RosSerialMsg, dst: &mut BytesMut) -> Result<()> {
+        let len = u16::try_from(item.msg.len()).map_err(|_| MessageTooLong(item.msg.len()))?;
+
         dst.put_u8(HEADER);
         dst.put_u8(PROTOCOL_VERSION_2);
 
-        // hack(ish) way of sending raw messages
-        if item.topic.is_none() {
-            dst.put_slice(item.msg.as_slice());
-            return Ok(());
-        }
-
-        let length_bytes = (item.msg.len() as u16).to_le_bytes();
+        let length_bytes = len.to_le_bytes();
         dst.put_slice(length_bytes.as_slice());
 
-        let length_checksum = calc_checksum(length_bytes.iter().copied());
+        let length_checksum = 255 - calc_checksum(length_bytes.iter().copied());
         dst.put_u8(length_checksum);
 
-        let topic_bytes = item.topic.unwrap().to_le_bytes();
+        let topic_bytes = item.topic.to_le_bytes();
         dst.put_slice(topic_bytes.as_slice());
 
         dst.put_slice(item.msg.as_slice());
@@ -196,10 +220,146 @@ mod tests {
         let mut codec = RosSerialMsgCodec.framed(stream);
 
         if let Some(Ok(msg)) = codec.next().await {
-            assert_eq!(msg.topic, Some(10));
+            assert_eq!(msg.topic, 10);
             let count_nonzero = msg.msg.iter().filter(|&b| *b != b'\0').count();
             assert_eq!(count_nonzero, 0);
         }
         assert!(codec.next().await.is_none());
     }
+
+    fn encode(msg: RosSerialMsg) -> BytesMut {
+        let mut dst = BytesMut::new();
+        RosSerialMsgCodec.encode(msg, &mut dst).unwrap();
+        dst
+    }
+
+    #[test]
+    fn test_encode_handshake() {
+        let handshake = encode(RosSerialMsg {
+            topic: rosrust_msg::rosserial_msgs::TopicInfo::ID_PUBLISHER,
+            msg: Vec::new(),
+        });
+        assert_eq!(&handshake[..2], b"\xff\xfe");
+        assert_eq!(&handshake[2..], b"\x00\x00\xff\x00\x00\xff");
+    }
+
+    #[test]
+    fn test_encode_decode_roundtrip() {
+        for (topic, len) in [(10, 8), (100, 0), (125, 1), (200, 300), (u16::MAX, 40000)] {
+            for seed in [0u8, 1, 255] {
+                let msg = RosSerialMsg {
+                    topic,
+                    msg: (0..len).map(|i| (i as u8).wrapping_add(seed)).collect(),
+                };
+                let mut src = encode(msg.clone());
+                assert_eq!(RosSerialMsgCodec.decode(&mut src).unwrap(), Some(msg));
+                assert!(src.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_roundtrip_zero_checksum() {
+        // topic + body sum up to 255, thus the message checksum is 0
+        let msg = RosSerialMsg {
+            topic: 250,
+            msg: vec![5],
+        };
+        let mut src = encode(msg.clone());
+        assert_eq!(src.last(), Some(&0));
+        assert_eq!(RosSerialMsgCodec.decode(&mut src).unwrap(), Some(msg));
+    }
+
+    #[test]
+    fn test_encode_message_too_long() {
+        let msg = RosSerialMsg {
+            topic: 100,
+            msg: vec![0; u16::MAX as usize + 1],
+        };
+        let result = RosSerialMsgCodec.encode(msg, &mut BytesMut::new());
+        assert!(matches!(result, Err(MessageTooLong(65536))));
+    }
+
+    #[test]
+    fn test_decode_skips_garbage() {
+        let mut src = BytesMut::from(&b"\x12\xff\x00\xff\xfe\x05\x00\x00"[..]);
+        src.extend_from_slice(&encode(RosSerialMsg {
+            topic: 100,
+            msg: vec![1, 2, 3],
+        }));
+        let msg = RosSerialMsgCodec.decode(&mut src).unwrap();
+        assert_eq!(
+            msg,
+            Some(RosSerialMsg {
+                topic: 100,
+                msg: vec![1, 2, 3],
+            })
+        );
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn test_decode_skips_invalid_message_checksum() {
+        let mut src = encode(RosSerialMsg {
+            topic: 100,
+            msg: vec![1, 2, 3],
+        });
+        *src.last_mut().unwrap() ^= 0x01;
+        src.extend_from_slice(&encode(RosSerialMsg {
+            topic: 101,
+            msg: Vec::new(),
+        }));
+        let msg = RosSerialMsgCodec.decode(&mut src).unwrap().unwrap();
+        assert_eq!(msg.topic, 101);
+    }
+
+    #[test]
+    fn test_decode_partial_frame() {
+        let msg = RosSerialMsg {
+            topic: 100,
+            msg: vec![1, 2, 3],
+        };
+        let frame = encode(msg.clone());
+        let mut src = BytesMut::new();
+        for byte in &frame[..frame.len() - 1] {
+            src.put_u8(*byte);
+            assert_eq!(RosSerialMsgCodec.decode(&mut src).unwrap(), None);
+        }
+        src.put_u8(*frame.last().unwrap());
+        assert_eq!(RosSerialMsgCodec.decode(&mut src).unwrap(), Some(msg));
+    }
+
+    /// The original implementation of [`calc_checksum`], kept to ensure that the wire format doesn't change.
+    fn calc_checksum_reference(bytes: impl Iterator<Item = u8>) -> u8 {
+        let mut checksum: u16 = 0;
+        for byte in bytes {
+            checksum += byte as u16;
+            checksum %= 256;
+        }
+        checksum as u8
+    }
+
+    #[test]
+    fn test_calc_checksum_matches_reference() {
+        assert_eq!(calc_checksum(std::iter::empty()), 0);
+        for len in [1, 2, 7, 255, 256, 257, 1000, 4096] {
+            for seed in [0u8, 1, 127, 255] {
+                let bytes: Vec<u8> = (0..len)
+                    .map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed))
+                    .collect();
+                assert_eq!(
+                    calc_checksum(bytes.iter().copied()),
+                    calc_checksum_reference(bytes.iter().copied()),
+                    "checksum mismatch for len {} and seed {}",
+                    len,
+                    seed
+                );
+            }
+        }
+        let all_ones = [255u8; 1000];
+        assert_eq!(
+            calc_checksum(all_ones.iter().copied()),
+            calc_checksum_reference(all_ones.iter().copied())
+        );
+    }
 }
@@ -7,10 +7,19 @@
 #![deny(unused)]
 
 mod codec;
+mod param;
+
+/// Internals exposed only for the benchmarks, not part of the public API.
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench {
+    pub use crate::codec::{RosSerialMsgCodec, calc_checksum};
+}
 
 use crate::codec::RosSerialMsgCodec;
+use crate::param::ParamCache;
 use futures::SinkExt;
-use log::{error, info, trace, warn};
+use log::{debug, error, info, trace, warn};
 use rosrust::error::ResponseError;
 use rosrust::{
     Publisher, RawMessage, RawMessageDescription, RosMsg, ros_debug, ros_err, ros_fatal, ros_info,
@@ -18,6 +27,10 @@ use rosrust::{
 };
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::sync::mpsc;
 use tokio_stream::StreamExt;
 use tokio_util::codec::{Decoder, Framed};
@@ -33,6 +46,8 @@ pub enum Error {
     RosError(Box<dyn std::error::Error>),
     /// The requested ROS parameter was not found
     RosParamNotFound(String),
+    /// The device did not send anything within the configured timeout.
+    Timeout,
 }
 
 impl Display for Error {
@@ -42,6 +57,7 @@ impl Display for Error {
             Error::IoError(_) => write!(f, "IO error"),
             Error::RosError(_) => write!(f, "ROS error"),
             Error::RosParamNotFound(p) => write!(f, "The ROS parameter {} was not found", p),
+            Error::Timeout => write!(f, "timed out waiting for the device"),
         }
     }
 }
@@ -87,8 +103,8 @@ pub type Result<T> = std::result::Result<T, Error>;
 /// Represents a ROS message as seen on ROS serial (i.e. as bytes).
 #[derive(Debug, Default, PartialEq, Clone)]
 pub struct RosSerialMsg {
-    /// The topic on which the message has been sent / will be published. If [`None`] then it is a raw message and will be sent 1:1.
-    pub topic: Option<u16>,
+    /// The topic on which the message has been sent / will be published.
+    pub topic: u16,
     /// The serialized message body.
     pub msg: Vec<u8>,
 }
@@ -99,36 +115,229 @@ impl From<RosSerialMsg> for RawMessage {
     }
 }
 
+/// What to do with a message received from ROS for a subscriber of the device if its channel is full.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriberOverflow {
+    /// Block the ROS subscription callback until there is room in the channel.
+    #[default]
+    Block,
+    /// Drop the newly received message and count it in [`Stats::dropped_subscriber_messages`].
+    DropNewest,
+}
+
+/// Statistics about a [`RosSerial`] connection, see [`RosSerial::stats`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Stats {
+    /// Number of messages received from ROS which were dropped because the channel of the subscriber was full.
+    pub dropped_subscriber_messages: u64,
+}
+
+/// A service endpoint which the device can set up, see [`RosSerial::unsupported_services`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ServiceEndpoint {
+    /// The publisher (i.e. the response) of a service server on the device.
+    ServerPublisher,
+    /// The subscriber (i.e. the request) of a service server on the device.
+    ServerSubscriber,
+    /// The publisher (i.e. the request) of a service client on the device.
+    ClientPublisher,
+    /// The subscriber (i.e. the response) of a service client on the device.
+    ClientSubscriber,
+}
+
+/// Bookkeeping of when the device last published a message on each topic.
+#[derive(Debug, Default)]
+struct LastSeen(HashMap<u16, Instant>);
+
+impl LastSeen {
+    fn record(&mut self, topic_id: u16) {
+        self.0.insert(topic_id, Instant::now());
+    }
+
+    fn age(&self, topic_id: u16) -> Option<Duration> {
+        self.0.get(&topic_id).map(Instant::elapsed)
+    }
+
+    fn ages(&self) -> HashMap<u16, Duration> {
+        self.0
+            .iter()
+            .map(|(topic_id, seen)| (*topic_id, seen.elapsed()))
+            .collect()
+    }
+}
+
+/// Default for [`RosSerialBuilder::topics_request_cooldown`].
+pub const DEFAULT_TOPICS_REQUEST_COOLDOWN: Duration = Duration::from_secs(1);
+
+/// Builder for a [`RosSerial`] connection, see [`RosSerial::builder`].
+#[derive(Debug)]
+pub struct RosSerialBuilder<S> {
+    serial: S,
+    handshake_timeout: Option<Duration>,
+    params: HashMap<String, rosrust_msg::rosserial_msgs::RequestParamRes>,
+    topics_request_cooldown: Duration,
+    subscriber_overflow: SubscriberOverflow,
+    subscriber_overflow_per_topic: HashMap<String, SubscriberOverflow>,
+}
+
+impl<S> RosSerialBuilder<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Wait for the device to send at least one valid frame within `timeout` before [`Self::build`] returns.
+    ///
+    /// If the device stays silent [`Self::build`] fails with [`Error::Timeout`].
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = Some(timeout);
+        self
+    }
+
+    /// Pre-seed parameters which are served to the device without looking them up on the ROS master.
+    pub fn params(
+        mut self,
+        params: HashMap<String, rosrust_msg::rosserial_msgs::RequestParamRes>,
+    ) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Minimum time between two requests for the topics when the device sends messages on unknown topics.
+    ///
+    /// Messages on unknown topics received in the meantime are dropped. Defaults to
+    /// [`DEFAULT_TOPICS_REQUEST_COOLDOWN`].
+    pub fn topics_request_cooldown(mut self, cooldown: Duration) -> Self {
+        self.topics_request_cooldown = cooldown;
+        self
+    }
+
+    /// What to do when the channel for a subscriber is full. Defaults to [`SubscriberOverflow::Block`].
+    pub fn subscriber_overflow(mut self, overflow: SubscriberOverflow) -> Self {
+        self.subscriber_overflow = overflow;
+        self
+    }
+
+    /// Override [`Self::subscriber_overflow`] for a specific topic.
+    pub fn subscriber_overflow_for(
+        mut self,
+        topic_name: &str,
+        overflow: SubscriberOverflow,
+    ) -> Self {
+        self.subscriber_overflow_per_topic
+            .insert(topic_name.to_string(), overflow);
+        self
+    }
+
+    /// Create the ROS Serial connection and request the topics from the device.
+    pub async fn build(self) -> Result<RosSerial<S>> {
+        let mut this = RosSerial {
+            serial: RosSerialMsgCodec.framed(self.serial),
+            publishers: HashMap::new(),
+            subscribers: HashMap::new(),
+            params: ParamCache::new(self.params),
+            last_seen: LastSeen::default(),
+            topics_request_cooldown: self.topics_request_cooldown,
+            last_topics_request: None,
+            subscriber_overflow: self.subscriber_overflow,
+            subscriber_overflow_per_topic: self.subscriber_overflow_per_topic,
+            dropped_subscriber_messages: Arc::new(AtomicU64::new(0)),
+            unsupported_services: Vec::new(),
+            unsupported_services_pending: false,
+        };
+        this.send_handshake().await?;
+        if let Some(timeout) = self.handshake_timeout {
+            this.wait_for_device(timeout).await?;
+        }
+        Ok(this)
+    }
+}
+
 /// Represents a ROS Serial connection to a serial port.
 ///
 /// To use this you _must_ have a running ROS core and have initialized a ROS node using [`rosrust`].
 //#[derive(Debug)]
 #[allow(missing_debug_implementations)]
-pub struct RosSerial {
-    serial: Framed<tokio_serial::SerialStream, RosSerialMsgCodec>,
+pub struct RosSerial<S = tokio_serial::SerialStream> {
+    serial: Framed<S, RosSerialMsgCodec>,
     publishers: HashMap<u16, Publisher<RawMessage>>,
     subscribers: HashMap<u16, mpsc::Receiver<RawMessage>>,
+    params: ParamCache,
+    last_seen: LastSeen,
+    topics_request_cooldown: Duration,
+    last_topics_request: Option<Instant>,
+    subscriber_overflow: SubscriberOverflow,
+    subscriber_overflow_per_topic: HashMap<String, SubscriberOverflow>,
+    dropped_subscriber_messages: Arc<AtomicU64>,
+    unsupported_services: Vec<(ServiceEndpoint, rosrust_msg::rosserial_msgs::TopicInfo)>,
+    unsupported_services_pending: bool,
 }
 
-impl RosSerial
-//where
-//    F: AsyncFnMut(&str, &str, Vec<u8>) -> Result<()>
+impl<S> RosSerial<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    //    F: AsyncFnMut(&str, &str, Vec<u8>) -> Result<()>
 {
     /// Create a new ROS Serial connection.
-    pub async fn new(serial: tokio_serial::SerialStream) -> Result<Self> {
-        let serial = RosSerialMsgCodec.framed(serial);
+    ///
+    /// This only sends the request for the topics and does not wait for the device to answer, use
+    /// [`RosSerialBuilder::handshake_timeout`] if you need to know whether the device is responsive.
+    pub async fn new(serial: S) -> Result<Self> {
+        Self::builder(serial).build().await
+    }
 
-        let mut this = RosSerial {
+    /// Create a builder to configure a new ROS Serial connection.
+    pub fn builder(serial: S) -> RosSerialBuilder<S> {
+        RosSerialBuilder {
             serial,
-            publishers: HashMap::new(),
-            subscribers: HashMap::new(),
-        };
-        this.request_topics().await?;
-        Ok(this)
+            handshake_timeout: None,
+            params: HashMap::new(),
+            topics_request_cooldown: DEFAULT_TOPICS_REQUEST_COOLDOWN,
+            subscriber_overflow: SubscriberOverflow::default(),
+            subscriber_overflow_per_topic: HashMap::new(),
+        }
+    }
+
+    /// Remove a parameter from the cache so that it will be looked up again on the next request by the device.
+    pub fn invalidate_param(&mut self, name: &str) {
+        self.params.invalidate(name);
+    }
+
+    /// Remove all parameters from the cache. Pre-seeded parameters are not affected.
+    pub fn invalidate_params(&mut self) {
+        self.params.clear();
+    }
+
+    /// Time since the device last published a message on the given topic, [`None`] if it never did.
+    pub fn last_seen(&self, topic_id: u16) -> Option<Duration> {
+        self.last_seen.age(topic_id)
+    }
+
+    /// Time since the device last published a message, for all topics on which it did so.
+    pub fn last_seen_all(&self) -> HashMap<u16, Duration> {
+        self.last_seen.ages()
+    }
+
+    /// Get the current statistics of the connection.
+    pub fn stats(&self) -> Stats {
+        Stats {
+            dropped_subscriber_messages: self.dropped_subscriber_messages.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The service endpoints which the device tried to set up but which are not yet supported.
+    pub fn unsupported_services(
+        &self,
+    ) -> &[(ServiceEndpoint, rosrust_msg::rosserial_msgs::TopicInfo)] {
+        &self.unsupported_services
     }
 
     /// Run the communication
     pub async fn run(&mut self) -> Result<()> {
+        let result = self.receive_msgs().await;
+        self.report_unsupported_services();
+        result
+    }
+
+    async fn receive_msgs(&mut self) -> Result<()> {
         while let Some(Ok(msg)) = self.serial.next().await {
             trace!("received message: {:?}", msg);
             self.handle_msg(msg).await?;
@@ -137,6 +346,18 @@ impl RosSerial
         Ok(())
     }
 
+    /// Wait for the first valid frame from the device. Invalid frames (e.g. stale data in the OS buffer) are skipped by
+    /// the codec, thus this only fails on IO errors or if nothing valid arrives before the timeout.
+    async fn wait_for_device(&mut self, timeout: Duration) -> Result<()> {
+        let msg = match tokio::time::timeout(timeout, self.serial.next()).await {
+            Ok(Some(msg)) => msg?,
+            Ok(None) => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+            Err(_) => return Err(Error::Timeout),
+        };
+        trace!("received first message: {:?}", msg);
+        self.handle_msg(msg).await
+    }
+
     async fn handle_msg(&mut self, msg: RosSerialMsg) -> Result<()> {
         const ID_SERVICE_SERVER_PUBLISHER: u16 =
             rosrust_msg::rosserial_msgs::TopicInfo::ID_SERVICE_SERVER
@@ -150,54 +371,115 @@ impl RosSerial
         const ID_SERVICE_CLIENT_SUBSCRIBER: u16 =
             rosrust_msg::rosserial_msgs::TopicInfo::ID_SERVICE_CLIENT
                 + rosrust_msg::rosserial_msgs::TopicInfo::ID_SUBSCRIBER;
+        // the device sends all topics (incl. services) at once, anything else means that the negotiation is over
+        let negotiation = matches!(
+            msg.topic,
+            rosrust_msg::rosserial_msgs::TopicInfo::ID_PUBLISHER
+                | rosrust_msg::rosserial_msgs::TopicInfo::ID_SUBSCRIBER
+                | ID_SERVICE_SERVER_PUBLISHER
+                | ID_SERVICE_SERVER_SUBSCRIBER
+                | ID_SERVICE_CLIENT_PUBLISHER
+                | ID_SERVICE_CLIENT_SUBSCRIBER
+        );
+        if !negotiation {
+            self.report_unsupported_services();
+        }
+
         match msg.topic {
-            Some(rosrust_msg::rosserial_msgs::TopicInfo::ID_TIME) => {
-                self.handle_time_request().await?
-            }
-            Some(rosrust_msg::rosserial_msgs::TopicInfo::ID_LOG) => {
+            rosrust_msg::rosserial_msgs::TopicInfo::ID_TIME => self.handle_time_request().await?,
+            rosrust_msg::rosserial_msgs::TopicInfo::ID_LOG => {
                 self.handle_logging_request(msg).await?
             }
-            Some(rosrust_msg::rosserial_msgs::TopicInfo::ID_PUBLISHER) => {
+            rosrust_msg::rosserial_msgs::TopicInfo::ID_PUBLISHER => {
                 self.setup_publisher(msg).await?
             }
-            Some(rosrust_msg::rosserial_msgs::TopicInfo::ID_SUBSCRIBER) => {
+            rosrust_msg::rosserial_msgs::TopicInfo::ID_SUBSCRIBER => {
                 self.setup_subscriber(msg).await?
             }
-            Some(rosrust_msg::rosserial_msgs::TopicInfo::ID_PARAMETER_REQUEST) => {
+            rosrust_msg::rosserial_msgs::TopicInfo::ID_PARAMETER_REQUEST => {
                 self.handle_parameter_request(msg).await?
             }
-            Some(ID_SERVICE_SERVER_PUBLISHER) => {
-                warn!("unimplemented ID_SERVICE_SERVER_PUBLISHER! {:?}", msg)
+            ID_SERVICE_SERVER_PUBLISHER => {
+                self.handle_unsupported_service(ServiceEndpoint::ServerPublisher, msg)
+                    .await?
             }
-            Some(ID_SERVICE_SERVER_SUBSCRIBER) => {
-                warn!("unimplemented ID_SERVICE_SERVER_SUBSCRIBER! {:?}", msg)
+            ID_SERVICE_SERVER_SUBSCRIBER => {
+                self.handle_unsupported_service(ServiceEndpoint::ServerSubscriber, msg)
+                    .await?
             }
-            Some(ID_SERVICE_CLIENT_PUBLISHER) => {
-                warn!("unimplemented ID_SERVICE_CLIENT_PUBLISHER! {:?}", msg)
+            ID_SERVICE_CLIENT_PUBLISHER => {
+                self.handle_unsupported_service(ServiceEndpoint::ClientPublisher, msg)
+                    .await?
             }
-            Some(ID_SERVICE_CLIENT_SUBSCRIBER) => {
-                warn!("unimplemented ID_SERVICE_CLIENT_SUBSCRIBER! {:?}", msg)
+            ID_SERVICE_CLIENT_SUBSCRIBER => {
+                self.handle_unsupported_service(ServiceEndpoint::ClientSubscriber, msg)
+                    .await?
             }
-            Some(t) => {
+            t => {
                 if let Some(publisher) = self.publishers.get(&t) {
                     trace!("forwarding (publishing) message on topic {}", t);
+                    self.last_seen.record(t);
                     publisher.send(msg.into())?;
+                } else if self
+                    .unsupported_services
+                    .iter()
+                    .any(|(_, topic_info)| topic_info.topic_id == t)
+                {
+                    trace!("dropping message on unsupported service topic {}", t);
                 } else {
-                    warn!("unknown topic: {:?}", t);
-                    self.request_topics().await?;
+                    self.handle_unknown_topic(t).await?;
                 }
             }
-            _ => warn!("received unknown topic: {:?}", msg.topic),
         }
 
         Ok(())
     }
 
+    async fn handle_unsupported_service(
+        &mut self,
+        endpoint: ServiceEndpoint,
+        msg: RosSerialMsg,
+    ) -> Result<()> {
+        let topic_info = rosrust_msg::rosserial_msgs::TopicInfo::decode(&msg.msg[..])?;
+        if self
+            .unsupported_services
+            .iter()
+            .any(|(e, t)| *e == endpoint && t.topic_name == topic_info.topic_name)
+        {
+            trace!(
+                "ignoring {:?} on {}: services are not yet supported",
+                endpoint, topic_info.topic_name
+            );
+            return Ok(());
+        }
+
+        debug!(
+            "unimplemented {:?} on {} [{}]: services are not yet supported",
+            endpoint, topic_info.topic_name, topic_info.message_type
+        );
+        self.unsupported_services.push((endpoint, topic_info));
+        self.unsupported_services_pending = true;
+        Ok(())
+    }
+
+    async fn handle_unknown_topic(&mut self, topic: u16) -> Result<()> {
+        let now = Instant::now();
+        if self
+            .last_topics_request
+            .is_some_and(|last| now.duration_since(last) < self.topics_request_cooldown)
+        {
+            trace!("dropping message on unknown topic {}", topic);
+            return Ok(());
+        }
+        warn!("unknown topic: {:?}, requesting topics again", topic);
+        self.send_handshake().await
+    }
+
     async fn handle_time_request(&mut self) -> Result<()> {
         trace!("responding to time message");
         let time = rosrust::wall_time::now();
         let response = RosSerialMsg {
-            topic: Some(rosrust_msg::rosserial_msgs::TopicInfo::ID_TIME),
+            topic: rosrust_msg::rosserial_msgs::TopicInfo::ID_TIME,
             msg: time.encode_vec()?,
         };
         self.serial.send(response).await?;
@@ -265,13 +547,17 @@ impl RosSerial
         self.subscribers.insert(topic_info.topic_id, rx);
 
         let topic_name = topic_info.topic_name.clone();
+        let overflow = self
+            .subscriber_overflow_per_topic
+            .get(&topic_info.topic_name)
+            .copied()
+            .unwrap_or(self.subscriber_overflow);
+        let dropped = self.dropped_subscriber_messages.clone();
         rosrust::subscribe(
             topic_info.topic_name.as_str(),
             topic_info.buffer_size as usize,
             move |msg: RawMessage| {
-                tx.blocking_send(msg).unwrap_or_else(|e| {
-                    error!("failed to send message on topic {}: {}", topic_name, e)
-                });
+                forward_subscribed_msg(&tx, msg, overflow, &dropped, &topic_name);
             },
         )
         .map_err(|e| Error::RosError(e.into()))?;
@@ -281,40 +567,373 @@ impl RosSerial
     async fn handle_parameter_request(&mut self, msg: RosSerialMsg) -> Result<()> {
         let request = rosrust_msg::rosserial_msgs::RequestParamReq::decode(&msg.msg[..])?;
         trace!("handling parameter request: {:?}", request);
-        let param = rosrust::param(request.name.as_str())
-            .ok_or(Error::RosParamNotFound(request.name.clone()))?;
-        if !param.exists()? {
-            return Err(Error::RosParamNotFound(request.name.clone()));
-        }
-        warn!(
-            "parameter request for {} ignored: not yet implemented, we don't know how to decide on the correct data type!",
-            request.name
-        );
-        // TODO: figure out how to identify the data type of the parameter (not exposed by `rosrust`)
-        let response = rosrust_msg::rosserial_msgs::RequestParamRes {
-            floats: Vec::new(),
-            ints: Vec::new(),
-            strings: Vec::new(),
-        };
+        let response = self
+            .params
+            .get_or_fetch(request.name.as_str(), param::fetch_from_master)?;
         let response = RosSerialMsg {
-            topic: Some(rosrust_msg::rosserial_msgs::TopicInfo::ID_PARAMETER_REQUEST),
+            topic: rosrust_msg::rosserial_msgs::TopicInfo::ID_PARAMETER_REQUEST,
             msg: response.encode_vec()?,
         };
         self.serial.send(response).await?;
         Ok(())
     }
 
-    async fn send_raw(&mut self, data: &[u8]) -> Result<()> {
+    /// Request the topics from the device. The handshake is an empty message on the publisher topic.
+    ///
+    /// Every handshake (re-)starts the cooldown for requesting the topics again, see [`Self::handle_unknown_topic`].
+    async fn send_handshake(&mut self) -> Result<()> {
+        self.last_topics_request = Some(Instant::now());
         self.serial
             .send(RosSerialMsg {
-                topic: None,
-                msg: data.to_vec(),
+                topic: rosrust_msg::rosserial_msgs::TopicInfo::ID_PUBLISHER,
+                msg: Vec::new(),
             })
             .await?;
         Ok(())
     }
+}
+
+impl<S> RosSerial<S> {
+    /// Log a single warning listing all unsupported service endpoints if new ones have been requested since the last one.
+    ///
+    /// This is done once the negotiation is over (i.e. on the first other message), when [`Self::run`] ends or when the
+    /// connection is dropped, whichever comes first. Returns the logged summary.
+    fn report_unsupported_services(&mut self) -> Option<String> {
+        if !self.unsupported_services_pending {
+            return None;
+        }
+        self.unsupported_services_pending = false;
+        let services = self
+            .unsupported_services
+            .iter()
+            .map(|(endpoint, topic_info)| {
+                format!(
+                    "{:?} on {} [{}]",
+                    endpoint, topic_info.topic_name, topic_info.message_type
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let summary = format!(
+            "the device requested {} service endpoint(s) which are not yet supported: {}",
+            self.unsupported_services.len(),
+            services
+        );
+        warn!("{}", summary);
+        Some(summary)
+    }
+}
+
+impl<S> Drop for RosSerial<S> {
+    fn drop(&mut self) {
+        self.report_unsupported_services();
+    }
+}
 
-    async fn request_topics(&mut self) -> Result<()> {
-        self.send_raw(b"\x00\x00\xff\x00\x00\xff").await
+/// Forward a message received from ROS to the channel of the subscriber, applying the overflow policy.
+fn forward_subscribed_msg(
+    tx: &mpsc::Sender<RawMessage>,
+    msg: RawMessage,
+    overflow: SubscriberOverflow,
+    dropped: &AtomicU64,
+    topic_name: &str,
+) {
+    match overflow {
+        SubscriberOverflow::Block => tx
+            .blocking_send(msg)
+            .unwrap_or_else(|e| error!("failed to send message on topic {}: {}", topic_name, e)),
+        SubscriberOverflow::DropNewest => match tx.try_send(msg) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                dropped.fetch_add(1, Ordering::Relaxed);
+                trace!("dropped message on topic {}: channel is full", topic_name);
+            }
+            Err(e) => error!("failed to send message on topic {}: {}", topic_name, e),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+    use tokio_util::bytes::BytesMut;
+    use tokio_util::codec::Encoder;
+
+    const TOPICS_REQUEST: &[u8] = b"\xff\xfe\x00\x00\xff\x00\x00\xff";
+
+    /// Encode a message as the device would send it.
+    fn frame(topic: u16, msg: Vec<u8>) -> BytesMut {
+        let mut dst = BytesMut::new();
+        RosSerialMsgCodec
+            .encode(RosSerialMsg { topic, msg }, &mut dst)
+            .unwrap();
+        dst
+    }
+
+    #[tokio::test]
+    async fn test_handshake_timeout_silent_device() {
+        let (host, mut device) = tokio::io::duplex(1024);
+        let result = RosSerial::builder(host)
+            .handshake_timeout(Duration::from_millis(10))
+            .build()
+            .await;
+        assert!(matches!(result, Err(Error::Timeout)));
+
+        let mut handshake = [0u8; 8];
+        device.read_exact(&mut handshake).await.unwrap();
+        assert_eq!(handshake, TOPICS_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_timeout_responsive_device() {
+        let (host, mut device) = tokio::io::duplex(1024);
+        device.write_all(&frame(200, Vec::new())).await.unwrap();
+        let result = RosSerial::builder(host)
+            .handshake_timeout(Duration::from_secs(1))
+            .build()
+            .await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_timeout_skips_garbage() {
+        let (host, mut device) = tokio::io::duplex(1024);
+        // the remainder of a frame sent before the port was opened, a stray header & a frame with a broken checksum
+        device
+            .write_all(b"\x03\x42\xff\x00\xff\xfe\x05\x00\x00")
+            .await
+            .unwrap();
+        device.write_all(&frame(200, Vec::new())).await.unwrap();
+        let result = RosSerial::builder(host)
+            .handshake_timeout(Duration::from_secs(1))
+            .build()
+            .await;
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_last_seen() {
+        let mut last_seen = LastSeen::default();
+        assert_eq!(last_seen.age(100), None);
+        assert!(last_seen.ages().is_empty());
+
+        last_seen.record(100);
+        last_seen.record(101);
+        let age = last_seen.age(100).unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(last_seen.age(100).unwrap() > age);
+        assert_eq!(last_seen.age(102), None);
+
+        let mut topics: Vec<u16> = last_seen.ages().into_keys().collect();
+        topics.sort();
+        assert_eq!(topics, vec![100, 101]);
+    }
+
+    #[test]
+    fn test_subscriber_overflow_drop_newest() {
+        let (tx, mut rx) = mpsc::channel(2);
+        let dropped = AtomicU64::new(0);
+        for i in 0..5 {
+            forward_subscribed_msg(
+                &tx,
+                RawMessage(vec![i]),
+                SubscriberOverflow::DropNewest,
+                &dropped,
+                "/foo",
+            );
+        }
+        assert_eq!(dropped.load(Ordering::Relaxed), 3);
+        assert_eq!(rx.try_recv().unwrap().0, vec![0]);
+        assert_eq!(rx.try_recv().unwrap().0, vec![1]);
+        assert!(rx.try_recv().is_err());
+    }
+
+    fn service_msg(
+        endpoint: u16,
+        topic_info: &rosrust_msg::rosserial_msgs::TopicInfo,
+    ) -> RosSerialMsg {
+        RosSerialMsg {
+            topic: rosrust_msg::rosserial_msgs::TopicInfo::ID_SERVICE_SERVER + endpoint,
+            msg: topic_info.encode_vec().unwrap(),
+        }
+    }
+
+    fn service_topic_info() -> rosrust_msg::rosserial_msgs::TopicInfo {
+        rosrust_msg::rosserial_msgs::TopicInfo {
+            topic_id: 100,
+            topic_name: "/foo".to_string(),
+            message_type: "std_srvs/Trigger".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_services_are_reported_once() {
+        let (host, device) = tokio::io::duplex(1024);
+        let mut rosserial = RosSerial::new(host).await.unwrap();
+        let topic_info = service_topic_info();
+        let publisher = service_msg(
+            rosrust_msg::rosserial_msgs::TopicInfo::ID_PUBLISHER,
+            &topic_info,
+        );
+        for _ in 0..10 {
+            rosserial.handle_msg(publisher.clone()).await.unwrap();
+        }
+        let subscriber = service_msg(
+            rosrust_msg::rosserial_msgs::TopicInfo::ID_SUBSCRIBER,
+            &topic_info,
+        );
+        rosserial.handle_msg(subscriber).await.unwrap();
+
+        assert_eq!(
+            rosserial.unsupported_services(),
+            &[
+                (ServiceEndpoint::ServerPublisher, topic_info.clone()),
+                (ServiceEndpoint::ServerSubscriber, topic_info.clone()),
+            ]
+        );
+        assert_eq!(
+            rosserial.report_unsupported_services().as_deref(),
+            Some(
+                "the device requested 2 service endpoint(s) which are not yet supported: \
+                 ServerPublisher on /foo [std_srvs/Trigger], ServerSubscriber on /foo [std_srvs/Trigger]"
+            )
+        );
+        assert_eq!(rosserial.report_unsupported_services(), None);
+
+        // calls on the service topic are dropped without requesting the topics again
+        let call = RosSerialMsg {
+            topic: topic_info.topic_id,
+            msg: vec![1, 2, 3],
+        };
+        rosserial.handle_msg(call).await.unwrap();
+        assert_eq!(count_topic_requests(rosserial, device).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_services_reported_after_negotiation() {
+        let (host, _device) = tokio::io::duplex(1024);
+        let mut rosserial = RosSerial::new(host).await.unwrap();
+        let topic_info = service_topic_info();
+        let publisher = service_msg(
+            rosrust_msg::rosserial_msgs::TopicInfo::ID_PUBLISHER,
+            &topic_info,
+        );
+        rosserial.handle_msg(publisher).await.unwrap();
+
+        // the first message after the negotiation triggers the summary, thus there is nothing left to report
+        let other = RosSerialMsg {
+            topic: 200,
+            msg: Vec::new(),
+        };
+        rosserial.handle_msg(other).await.unwrap();
+        assert_eq!(rosserial.report_unsupported_services(), None);
+    }
+
+    #[tokio::test]
+    async fn test_parameter_requests_are_answered_from_seeded_params() {
+        let (host, mut device) = tokio::io::duplex(1024);
+        let seeded = rosrust_msg::rosserial_msgs::RequestParamRes {
+            ints: vec![1, 2],
+            floats: vec![0.5],
+            strings: vec!["bar".to_string()],
+        };
+        let mut rosserial = RosSerial::builder(host)
+            .params(HashMap::from([("/foo".to_string(), seeded.clone())]))
+            .build()
+            .await
+            .unwrap();
+        let request = rosrust_msg::rosserial_msgs::RequestParamReq {
+            name: "/foo".to_string(),
+        };
+        let request = RosSerialMsg {
+            topic: rosrust_msg::rosserial_msgs::TopicInfo::ID_PARAMETER_REQUEST,
+            msg: request.encode_vec().unwrap(),
+        };
+        rosserial.handle_msg(request.clone()).await.unwrap();
+        rosserial.handle_msg(request).await.unwrap();
+        drop(rosserial);
+
+        let mut sent = Vec::new();
+        device.read_to_end(&mut sent).await.unwrap();
+        let mut sent = BytesMut::from(sent.as_slice());
+        let mut frames = Vec::new();
+        while let Some(msg) = RosSerialMsgCodec.decode(&mut sent).unwrap() {
+            frames.push(msg);
+        }
+
+        // the initial topics request followed by the two responses
+        assert_eq!(frames.len(), 3);
+        assert_eq!(
+            frames[0].topic,
+            rosrust_msg::rosserial_msgs::TopicInfo::ID_PUBLISHER
+        );
+        for response in &frames[1..] {
+            assert_eq!(
+                response.topic,
+                rosrust_msg::rosserial_msgs::TopicInfo::ID_PARAMETER_REQUEST
+            );
+            let response =
+                rosrust_msg::rosserial_msgs::RequestParamRes::decode(&response.msg[..]).unwrap();
+            assert_eq!(response, seeded);
+        }
+    }
+
+    async fn count_topic_requests(
+        rosserial: RosSerial<DuplexStream>,
+        mut device: DuplexStream,
+    ) -> usize {
+        drop(rosserial);
+        let mut sent = Vec::new();
+        device.read_to_end(&mut sent).await.unwrap();
+        assert_eq!(sent.len() % TOPICS_REQUEST.len(), 0);
+        assert!(
+            sent.chunks(TOPICS_REQUEST.len())
+                .all(|c| c == TOPICS_REQUEST)
+        );
+        sent.len() / TOPICS_REQUEST.len()
+    }
+
+    #[tokio::test]
+    async fn test_unknown_topics_request_topics_once() {
+        let (host, device) = tokio::io::duplex(1024);
+        let mut rosserial = RosSerial::builder(host)
+            .topics_request_cooldown(Duration::from_secs(60))
+            .build()
+            .await
+            .unwrap();
+        for _ in 0..100 {
+            let msg = RosSerialMsg {
+                topic: 200,
+                msg: vec![1, 2, 3],
+            };
+            rosserial.handle_msg(msg).await.unwrap();
+        }
+
+        // only the initial request, the unknown topics all arrived within its cooldown
+        assert_eq!(count_topic_requests(rosserial, device).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_topics_request_topics_after_cooldown() {
+        let (host, device) = tokio::io::duplex(1024);
+        let mut rosserial = RosSerial::builder(host)
+            .topics_request_cooldown(Duration::from_millis(10))
+            .build()
+            .await
+            .unwrap();
+        let msg = RosSerialMsg {
+            topic: 200,
+            msg: Vec::new(),
+        };
+        rosserial.handle_msg(msg.clone()).await.unwrap();
+        rosserial.handle_msg(msg.clone()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        rosserial.handle_msg(msg.clone()).await.unwrap();
+        rosserial.handle_msg(msg).await.unwrap();
+
+        // the initial request & a single re-request once the cooldown expired
+        assert_eq!(count_topic_requests(rosserial, device).await, 2);
     }
 }
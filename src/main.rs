@@ -7,20 +7,101 @@ use tokio_serial::SerialPortBuilderExt;
 use url::Url;
 
 const ROS_MASTER_URI: &str = "http://0.0.0.0:11311";
+const DEFAULT_NODE_NAME: &str = "rosserial_rs";
+const DEFAULT_QUEUE_SIZE: usize = 1000;
+const USAGE: &str = "usage: rosserial-rs [--node-name <name> | --node-name-from-port] [--queue-size <size>] <port>
+  --node-name <name>     name of the ROS node (default: rosserial_rs, kept for existing launch files & tooling)
+  --node-name-from-port  derive the node name from the port (e.g. rosserial_rs_dev_ttyUSB0 for /dev/ttyUSB0)
+
+When running several bridges (one per device) each needs a distinct node name, otherwise ROS shuts down the earlier
+ones: pass --node-name-from-port (or a distinct --node-name) to each of them.
+  --queue-size <size>    capacity passed to rosrust::loop_init, must be greater than 0 (default: 1000)";
+
+/// The command line arguments.
+#[derive(Debug, PartialEq)]
+struct Args {
+    port: String,
+    node_name: String,
+    queue_size: usize,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, Box<dyn Error>> {
+        let mut port = None;
+        let mut node_name = None;
+        let mut node_name_from_port = false;
+        let mut queue_size = DEFAULT_QUEUE_SIZE;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--node-name" => {
+                    let name = args.next().ok_or("--node-name requires a value")?;
+                    if !is_valid_node_name(&name) {
+                        return Err(format!(
+                            "invalid node name: {:?} (must start with a letter, followed by letters, digits, '_' or '/')",
+                            name
+                        )
+                        .into());
+                    }
+                    node_name = Some(name);
+                }
+                "--node-name-from-port" => node_name_from_port = true,
+                "--queue-size" => {
+                    queue_size = args
+                        .next()
+                        .ok_or("--queue-size requires a value")?
+                        .parse()?;
+                    if queue_size == 0 {
+                        return Err("--queue-size must be greater than 0".into());
+                    }
+                }
+                _ if port.is_none() => port = Some(arg),
+                _ => return Err(format!("unexpected argument: {}", arg).into()),
+            }
+        }
+        let port = port.ok_or("expected the path to / identifier of the serial port")?;
+        let node_name = match (node_name, node_name_from_port) {
+            (Some(_), true) => {
+                return Err("--node-name and --node-name-from-port are mutually exclusive".into());
+            }
+            (Some(node_name), false) => node_name,
+            (None, true) => node_name_for_port(&port),
+            (None, false) => DEFAULT_NODE_NAME.to_string(),
+        };
+        Ok(Args {
+            port,
+            node_name,
+            queue_size,
+        })
+    }
+}
+
+/// Check that the name is a valid ROS graph resource name, otherwise `rosrust::loop_init` would retry forever.
+fn is_valid_node_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '/')
+}
+
+/// Derive a node name from the port so that multiple bridges (one per device) don't kill each other.
+fn node_name_for_port(port: &str) -> String {
+    let suffix: String = port
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let suffix = suffix.trim_matches('_');
+    if suffix.is_empty() {
+        DEFAULT_NODE_NAME.to_string()
+    } else {
+        format!("{}_{}", DEFAULT_NODE_NAME, suffix)
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     env_logger::init();
 
-    let args: Vec<String> = env::args().skip(1).collect();
-    if args.len() != 1 {
-        error!(
-            "expected exactly one argument: the path to / identifier of the serial port, but got {} args",
-            args.len()
-        );
-        return Err("expected exactly one argument".into());
-    }
-    let port = args.first().unwrap();
+    let args = Args::parse(env::args().skip(1))
+        .inspect_err(|e| error!("invalid arguments: {}\n{}", e, USAGE))?;
 
     // Spawn a Tokio task to run the ROS master
     let core_cancel = tokio_util::sync::CancellationToken::new();
@@ -42,12 +123,16 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     });
 
-    tokio::task::spawn_blocking(|| {
-        rosrust::loop_init("rosserial_rs", 1000);
+    info!("starting node {} for port {}", args.node_name, args.port);
+    tokio::task::spawn_blocking({
+        let node_name = args.node_name.clone();
+        move || {
+            rosrust::loop_init(&node_name, args.queue_size);
+        }
     })
     .await?;
 
-    let port = tokio_serial::new(port, 115200).open_native_async()?;
+    let port = tokio_serial::new(&args.port, 115200).open_native_async()?;
 
     let mut rosserial = RosSerial::new(port).await?;
 
@@ -72,3 +157,83 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, Box<dyn Error>> {
+        Args::parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn test_parse_defaults() {
+        assert_eq!(
+            parse(&["/dev/ttyUSB0"]).unwrap(),
+            Args {
+                port: "/dev/ttyUSB0".to_string(),
+                node_name: DEFAULT_NODE_NAME.to_string(),
+                queue_size: DEFAULT_QUEUE_SIZE,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_options_before_and_after_port() {
+        let expected = Args {
+            port: "/dev/ttyUSB0".to_string(),
+            node_name: "foo".to_string(),
+            queue_size: 10,
+        };
+        let before = parse(&["--node-name", "foo", "--queue-size", "10", "/dev/ttyUSB0"]);
+        assert_eq!(before.unwrap(), expected);
+        let after = parse(&["/dev/ttyUSB0", "--node-name", "foo", "--queue-size", "10"]);
+        assert_eq!(after.unwrap(), expected);
+        let mixed = parse(&["--queue-size", "10", "/dev/ttyUSB0", "--node-name", "foo"]);
+        assert_eq!(mixed.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_node_name_from_port() {
+        let args = parse(&["--node-name-from-port", "/dev/ttyUSB0"]).unwrap();
+        assert_eq!(args.node_name, "rosserial_rs_dev_ttyUSB0");
+        assert!(parse(&["--node-name-from-port", "--node-name", "foo", "COM3"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["/dev/ttyUSB0", "--node-name"]).is_err());
+        assert!(parse(&["/dev/ttyUSB0", "--queue-size"]).is_err());
+        assert!(parse(&["/dev/ttyUSB0", "--queue-size", "many"]).is_err());
+        assert!(parse(&["/dev/ttyUSB0", "--queue-size", "0"]).is_err());
+        assert!(parse(&["/dev/ttyUSB0", "/dev/ttyUSB1"]).is_err());
+        for name in ["", "my-node", "1node", "foo bar", "_foo"] {
+            assert!(
+                parse(&["/dev/ttyUSB0", "--node-name", name]).is_err(),
+                "node name {:?} should be rejected",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_valid_node_names() {
+        for name in ["foo", "foo_bar", "foo/bar2"] {
+            let args = parse(&["/dev/ttyUSB0", "--node-name", name]).unwrap();
+            assert_eq!(args.node_name, name);
+        }
+    }
+
+    #[test]
+    fn test_node_name_for_port() {
+        assert_eq!(
+            node_name_for_port("/dev/ttyUSB0"),
+            "rosserial_rs_dev_ttyUSB0"
+        );
+        assert_eq!(node_name_for_port("COM3"), "rosserial_rs_COM3");
+        assert_eq!(node_name_for_port("/"), "rosserial_rs");
+        assert!(is_valid_node_name(&node_name_for_port("/dev/ttyUSB0")));
+        assert!(is_valid_node_name(DEFAULT_NODE_NAME));
+    }
+}
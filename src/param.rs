@@ -0,0 +1,138 @@
+//! Handling of the ROS parameters requested by the device.
+
+use crate::{Error, Result};
+use log::warn;
+use rosrust_msg::rosserial_msgs::RequestParamRes;
+use std::collections::HashMap;
+use xml_rpc::Value;
+
+/// Cache for the parameters requested by the device.
+///
+/// Devices tend to request the same parameters over and over again (e.g. on every restart), so the values are only
+/// looked up once and then served from the cache until they get invalidated. Seeded parameters are always served as-is
+/// and never looked up.
+#[derive(Debug, Default)]
+pub(crate) struct ParamCache {
+    seeded: HashMap<String, RequestParamRes>,
+    cached: HashMap<String, RequestParamRes>,
+}
+
+impl ParamCache {
+    pub(crate) fn new(seeded: HashMap<String, RequestParamRes>) -> Self {
+        ParamCache {
+            seeded,
+            cached: HashMap::new(),
+        }
+    }
+
+    /// Get the parameter from the cache or, if it isn't known yet, look it up using `fetch` and cache it.
+    pub(crate) fn get_or_fetch(
+        &mut self,
+        name: &str,
+        fetch: impl FnOnce(&str) -> Result<RequestParamRes>,
+    ) -> Result<RequestParamRes> {
+        if let Some(value) = self.seeded.get(name).or_else(|| self.cached.get(name)) {
+            return Ok(value.clone());
+        }
+        let value = fetch(name)?;
+        self.cached.insert(name.to_string(), value.clone());
+        Ok(value)
+    }
+
+    /// Remove a parameter from the cache so that it will be looked up again on the next request.
+    pub(crate) fn invalidate(&mut self, name: &str) {
+        self.cached.remove(name);
+    }
+
+    /// Remove all parameters from the cache.
+    pub(crate) fn clear(&mut self) {
+        self.cached.clear();
+    }
+}
+
+/// Look up the parameter on the ROS master.
+pub(crate) fn fetch_from_master(name: &str) -> Result<RequestParamRes> {
+    let param = rosrust::param(name).ok_or(Error::RosParamNotFound(name.to_string()))?;
+    if !param.exists()? {
+        return Err(Error::RosParamNotFound(name.to_string()));
+    }
+    Ok(to_response(name, param.get_raw()?))
+}
+
+/// Convert the parameter to the representation used by rosserial: booleans are sent as ints, lists are flattened.
+fn to_response(name: &str, value: Value) -> RequestParamRes {
+    let values = match value {
+        Value::Array(values) => values,
+        value => vec![value],
+    };
+
+    let mut response = RequestParamRes::default();
+    for value in values {
+        match value {
+            Value::Int(i) => response.ints.push(i),
+            Value::Bool(b) => response.ints.push(b as i32),
+            Value::Double(f) => response.floats.push(f as f32),
+            Value::String(s) => response.strings.push(s),
+            value => warn!("unsupported value for parameter {}: {:?}", name, value),
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_identical_requests_fetch_once() {
+        let mut cache = ParamCache::default();
+        let fetched = Cell::new(0);
+        let fetch = |_: &str| {
+            fetched.set(fetched.get() + 1);
+            Ok(RequestParamRes {
+                ints: vec![42],
+                ..Default::default()
+            })
+        };
+
+        let first = cache.get_or_fetch("/foo", fetch).unwrap();
+        let second = cache.get_or_fetch("/foo", fetch).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first.ints, vec![42]);
+        assert_eq!(fetched.get(), 1);
+
+        cache.invalidate("/foo");
+        cache.get_or_fetch("/foo", fetch).unwrap();
+        assert_eq!(fetched.get(), 2);
+    }
+
+    #[test]
+    fn test_seeded_params_are_not_fetched() {
+        let seeded = RequestParamRes {
+            strings: vec!["bar".to_string()],
+            ..Default::default()
+        };
+        let mut cache = ParamCache::new(HashMap::from([("/foo".to_string(), seeded.clone())]));
+        cache.clear();
+
+        let value = cache
+            .get_or_fetch("/foo", |name| {
+                Err(Error::RosParamNotFound(name.to_string()))
+            })
+            .unwrap();
+        assert_eq!(value, seeded);
+    }
+
+    #[test]
+    fn test_to_response() {
+        let value = Value::Array(vec![Value::Int(1), Value::Bool(true), Value::Double(0.5)]);
+        let response = to_response("/foo", value);
+        assert_eq!(response.ints, vec![1, 1]);
+        assert_eq!(response.floats, vec![0.5]);
+        assert!(response.strings.is_empty());
+
+        let response = to_response("/foo", Value::String("bar".to_string()));
+        assert_eq!(response.strings, vec!["bar".to_string()]);
+    }
+}